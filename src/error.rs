@@ -14,13 +14,82 @@
 
 //! # Error handling
 //!
-//! Some useful methods for creating Error objects
+//! Some useful methods for creating RpcError objects, plus a top-level
+//! `Error` enum covering everything that can go wrong making a call
+//! with this library, not just JSON-RPC protocol errors.
 //!
 
+use std::fmt;
+use std::io::IoError;
+
 use serialize::json;
 
 use {JsonResult, Response};
 
+/// Library-level errors: anything that can go wrong making a JSON-RPC
+/// call that doesn't come with a JSON-RPC error code of its own, because
+/// it happened below the protocol layer (bad HTTP status, unparseable
+/// body, mismatched request/response ids) or is simply a forwarded
+/// protocol-level `RpcError` from the server.
+#[deriving(Show)]
+pub enum Error {
+  /// The response body could not be parsed as JSON
+  Json(json::ParserError),
+  /// The HTTP transport returned a non-success status code
+  BadStatus(uint),
+  /// The underlying transport (socket, HTTP client, ...) failed
+  Transport(IoError),
+  /// The server replied with a JSON-RPC error object
+  Rpc(RpcError),
+  /// The response's `id` did not match the request's `id`
+  NonceMismatch
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Json(ref e) => write!(f, "invalid JSON: {}", e),
+      BadStatus(code) => write!(f, "unexpected HTTP status {}", code),
+      Transport(ref e) => write!(f, "transport error: {}", e),
+      Rpc(ref e) => write!(f, "{}", e),
+      NonceMismatch => write!(f, "response id did not match request id")
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn description(&self) -> &str {
+    match *self {
+      Json(_) => "invalid JSON",
+      BadStatus(_) => "bad HTTP status",
+      Transport(_) => "transport error",
+      Rpc(_) => "JSON-RPC error",
+      NonceMismatch => "response id mismatch"
+    }
+  }
+
+  fn cause(&self) -> Option<&std::error::Error> {
+    match *self {
+      Json(ref e) => Some(e as &std::error::Error),
+      Transport(ref e) => Some(e as &std::error::Error),
+      Rpc(ref e) => Some(e as &std::error::Error),
+      BadStatus(_) | NonceMismatch => None
+    }
+  }
+}
+
+impl std::error::FromError<json::ParserError> for Error {
+  fn from_error(err: json::ParserError) -> Error {
+    Json(err)
+  }
+}
+
+impl std::error::FromError<RpcError> for Error {
+  fn from_error(err: RpcError) -> Error {
+    Rpc(err)
+  }
+}
+
 /// Standard error responses, as described at at
 /// http://www.jsonrpc.org/specification#error_object
 ///
@@ -32,7 +101,8 @@ use {JsonResult, Response};
 /// The limited permissions granted above are perpetual and will not be revoked.
 /// 
 /// This document and the information contained herein is provided "AS IS" and ALL WARRANTIES, EXPRESS OR IMPLIED are DISCLAIMED, INCLUDING BUT NOT LIMITED TO ANY WARRANTY THAT THE USE OF THE INFORMATION HEREIN WILL NOT INFRINGE ANY RIGHTS OR ANY IMPLIED WARRANTIES OF MERCHANTABILITY OR FITNESS FOR A PARTICULAR PURPOSE.
-/// 
+///
+#[deriving(Clone, PartialEq, Show)]
 pub enum StandardError {
   /// Invalid JSON was received by the server.
   /// An error occurred on the server while parsing the JSON text.
@@ -44,12 +114,55 @@ pub enum StandardError {
   /// Invalid method parameter(s).
   InvalidParams,
   /// Internal JSON-RPC error.
-  InternalError
+  InternalError,
+  /// An implementation-defined server error. The spec reserves the
+  /// range -32000 to -32099 (inclusive) for these.
+  ServerError(i64)
 }
 
-#[deriving(Clone, Show, Encodable)]
+impl StandardError {
+  /// Classify a raw JSON-RPC error code as one of the standard errors,
+  /// turning any code in the reserved server range into a `ServerError`
+  /// and anything else into `None`.
+  pub fn from_code(i: int) -> Option<StandardError> {
+    match i {
+      -32700 => Some(ParseError),
+      -32600 => Some(InvalidRequest),
+      -32601 => Some(MethodNotFound),
+      -32602 => Some(InvalidParams),
+      -32603 => Some(InternalError),
+      _ if i >= -32099 && i <= -32000 => Some(ServerError(i as i64)),
+      _ => None
+    }
+  }
+
+  /// The raw JSON-RPC error code for this standard error.
+  ///
+  /// # Panics
+  /// Panics if `self` is a `ServerError` whose code falls outside the
+  /// reserved range -32000 to -32099, since such a value can never have
+  /// been produced by `from_code` and has no spec-compliant code to
+  /// return.
+  pub fn to_code(&self) -> int {
+    match *self {
+      ParseError => -32700,
+      InvalidRequest => -32600,
+      MethodNotFound => -32601,
+      InvalidParams => -32602,
+      InternalError => -32603,
+      ServerError(code) => {
+        if code < -32099 || code > -32000 {
+          fail!("ServerError code {} is outside the reserved range -32000 to -32099", code);
+        }
+        code as int
+      }
+    }
+  }
+}
+
+#[deriving(Clone, Show, Encodable, Decodable)]
 /// A JSONRPC error object
-pub struct Error {
+pub struct RpcError {
   /// The integer identifier of the error
   pub code: int,
   /// A string describing the error
@@ -59,33 +172,78 @@ pub struct Error {
 }
 
 /// Create a standard error responses
-pub fn standard_error(code: StandardError, data: Option<json::Json>) -> Error {
+pub fn standard_error(code: StandardError, data: Option<json::Json>) -> RpcError {
   match code {
-    ParseError => Error {
+    ParseError => RpcError {
       code: -32700,
       message: "Parse error".to_string(),
       data: data
     },
-    InvalidRequest => Error {
+    InvalidRequest => RpcError {
       code: -32600,
       message: "Invalid Request".to_string(),
       data: data
     },
-    MethodNotFound => Error {
+    MethodNotFound => RpcError {
       code: -32601,
       message: "Method not found".to_string(),
       data: data
     },
-    InvalidParams => Error {
+    InvalidParams => RpcError {
       code: -32602,
       message: "Invalid params".to_string(),
       data: data
     },
-    InternalError => Error {
+    InternalError => RpcError {
       code: -32603,
       message: "Internal error".to_string(),
       data: data
     },
+    ServerError(code) => {
+      if code < -32099 || code > -32000 {
+        fail!("ServerError code {} is outside the reserved range -32000 to -32099", code);
+      }
+      RpcError {
+        code: code as int,
+        message: "Server error".to_string(),
+        data: data
+      }
+    }
+  }
+}
+
+impl RpcError {
+  /// Create an error with an arbitrary code, message and data. Useful
+  /// when the standard error objects don't fit, e.g. for `ServerError`
+  /// codes that need an application-specific message.
+  pub fn new(code: int, message: String, data: Option<json::Json>) -> RpcError {
+    RpcError { code: code, message: message, data: data }
+  }
+
+  /// Create an "Invalid params" error carrying a custom message instead
+  /// of the hardcoded "Invalid params" text.
+  pub fn invalid_params(message: String, data: Option<json::Json>) -> RpcError {
+    RpcError { code: -32602, message: message, data: data }
+  }
+}
+
+impl fmt::Display for RpcError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.code, self.message)
+  }
+}
+
+impl std::error::Error for RpcError {
+  fn description(&self) -> &str {
+    self.message.as_slice()
+  }
+}
+
+impl std::error::FromError<json::ParserError> for RpcError {
+  /// A parse failure turns into a well-formed `ParseError` response
+  /// object, so it can be handed straight back to the client.
+  fn from_error(err: json::ParserError) -> RpcError {
+    standard_error(ParseError, Some(json::String(format!("{}", err))))
   }
 }
 
@@ -97,11 +255,28 @@ pub fn result_to_response(result: JsonResult<json::Json>, id: json::Json) -> Res
   }
 }
 
+/// Converts a batch of Rust `Result`s to a JSON-RPC batch response.
+///
+/// Each entry pairs a result with `Some(id)` for an ordinary request, or
+/// `None` for a notification. Per the spec, an empty batch produces a
+/// single `InvalidRequest` error response with a `null` id, and
+/// notification entries are omitted from the output array entirely.
+pub fn results_to_batch(results: Vec<(JsonResult<json::Json>, Option<json::Json>)>) -> Vec<Response> {
+  if results.is_empty() {
+    return vec![result_to_response(Err(standard_error(InvalidRequest, None)), json::Null)];
+  }
+
+  results.move_iter().filter_map(|(result, id)| {
+    id.map(|id| result_to_response(result, id))
+  }).collect()
+}
+
 #[cfg(test)]
 mod tests {
-  use super::{ParseError, InvalidRequest, MethodNotFound, InvalidParams, InternalError};
-  use super::{standard_error, result_to_response};
+  use super::{ParseError, InvalidRequest, MethodNotFound, InvalidParams, InternalError, ServerError};
+  use super::{standard_error, result_to_response, results_to_batch, RpcError, StandardError, Rpc};
 
+  use std::error::FromError;
   use serialize::json;
 
   #[test]
@@ -148,5 +323,95 @@ mod tests {
     assert_eq!(resp.id, json::I64(-1));
     assert_eq!(resp.error.get_ref().code, -32603);
   }
+
+  #[test]
+  fn test_server_error() {
+    let resp = result_to_response(Err(standard_error(ServerError(-32050), None)), json::U64(1));
+    assert!(resp.result.is_none());
+    assert!(resp.error.is_some());
+    assert_eq!(resp.id, json::U64(1));
+    assert_eq!(resp.error.get_ref().code, -32050);
+  }
+
+  #[test]
+  fn test_invalid_params_custom_message() {
+    let err = RpcError::invalid_params("bad `foo` parameter".to_string(), None);
+    assert_eq!(err.code, -32602);
+    assert_eq!(err.message, "bad `foo` parameter".to_string());
+  }
+
+  #[test]
+  fn test_from_code_round_trip() {
+    assert_eq!(StandardError::from_code(-32700), Some(ParseError));
+    assert_eq!(StandardError::from_code(-32600), Some(InvalidRequest));
+    assert_eq!(StandardError::from_code(-32601), Some(MethodNotFound));
+    assert_eq!(StandardError::from_code(-32602), Some(InvalidParams));
+    assert_eq!(StandardError::from_code(-32603), Some(InternalError));
+    assert_eq!(StandardError::from_code(-32050), Some(ServerError(-32050)));
+    assert_eq!(StandardError::from_code(0), None);
+
+    assert_eq!(ParseError.to_code(), -32700);
+    assert_eq!(ServerError(-32050).to_code(), -32050);
+  }
+
+  #[test]
+  #[should_fail]
+  fn test_to_code_rejects_out_of_range_server_error() {
+    ServerError(12345).to_code();
+  }
+
+  #[test]
+  fn test_decodes_error_object_off_the_wire() {
+    let decoded: RpcError = json::decode("{\"code\":-32601,\"message\":\"Method not found\",\"data\":null}").unwrap();
+    assert_eq!(decoded.code, -32601);
+    assert_eq!(decoded.message, "Method not found".to_string());
+    assert_eq!(StandardError::from_code(decoded.code), Some(MethodNotFound));
+
+    let decoded_server_err: RpcError = json::decode("{\"code\":-32050,\"message\":\"oops\",\"data\":null}").unwrap();
+    assert_eq!(StandardError::from_code(decoded_server_err.code), Some(ServerError(-32050)));
+  }
+
+  #[test]
+  fn test_wraps_rpc_error() {
+    let rpc_err = standard_error(MethodNotFound, None);
+    match Rpc(rpc_err) {
+      Rpc(e) => assert_eq!(e.code, -32601),
+      _ => fail!("expected Rpc variant")
+    }
+  }
+
+  #[test]
+  fn test_empty_batch_is_invalid_request() {
+    let batch = results_to_batch(vec![]);
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].id, json::Null);
+    assert_eq!(batch[0].error.get_ref().code, -32600);
+  }
+
+  #[test]
+  fn test_batch_omits_notifications() {
+    let batch = results_to_batch(vec![
+      (Ok(json::U64(1)), Some(json::U64(1))),
+      (Ok(json::U64(2)), None),
+      (Err(standard_error(InternalError, None)), Some(json::U64(3)))
+    ]);
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[0].id, json::U64(1));
+    assert_eq!(batch[1].id, json::U64(3));
+    assert_eq!(batch[1].error.get_ref().code, -32603);
+  }
+
+  #[test]
+  fn test_display() {
+    let err = standard_error(MethodNotFound, None);
+    assert_eq!(format!("{}", err), "-32601: Method not found".to_string());
+  }
+
+  #[test]
+  fn test_from_parser_error() {
+    let parse_err = json::from_str("not json").unwrap_err();
+    let err: RpcError = FromError::from_error(parse_err);
+    assert_eq!(err.code, -32700);
+  }
 }
 